@@ -0,0 +1,123 @@
+use std::{
+    cmp,
+    time::{Duration, Instant},
+};
+
+/// Token-bucket pacer that spreads a connection's transmissions over a congestion window's
+/// worth of RTT, rather than sending the whole window as a single burst.
+///
+/// The bucket refills continuously at `pacing_gain * cwnd / smoothed_rtt` bytes/sec, capped at
+/// [`BURST_BUDGET_PACKETS`] MTU-sized datagrams so that a connection idle for a while doesn't
+/// accumulate enough tokens to produce a thundering burst once it resumes sending.
+pub(super) struct Pacer {
+    capacity: u64,
+    tokens: u64,
+    prev: Instant,
+}
+
+impl Pacer {
+    pub(super) fn new(now: Instant, mtu: u16) -> Self {
+        let capacity = BURST_BUDGET_PACKETS * u64::from(mtu);
+        Self {
+            capacity,
+            tokens: capacity,
+            prev: now,
+        }
+    }
+
+    /// Returns `None` if a `size`-byte datagram can be sent now, or `Some(duration)` for how
+    /// long the caller should wait before enough tokens will have accumulated.
+    ///
+    /// On a `None` result, `size` bytes are deducted from the bucket; callers that decide not to
+    /// send after all should not call this again until they actually do, since tokens are not
+    /// refunded.
+    pub(super) fn poll_transmit(
+        &mut self,
+        now: Instant,
+        size: u64,
+        cwnd: u64,
+        smoothed_rtt: Duration,
+        in_slow_start: bool,
+    ) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.prev);
+        self.prev = now;
+
+        let rate = pacing_rate(cwnd, smoothed_rtt, in_slow_start);
+        let refill = (rate as u128 * elapsed.as_nanos() / Duration::from_secs(1).as_nanos())
+            .min(u64::MAX as u128) as u64;
+        self.tokens = cmp::min(self.tokens.saturating_add(refill), self.capacity);
+
+        if self.tokens >= size {
+            self.tokens -= size;
+            return None;
+        }
+
+        let missing = size - self.tokens;
+        let nanos =
+            (u128::from(missing) * Duration::from_secs(1).as_nanos()) / u128::from(rate.max(1));
+        Some(Duration::from_nanos(nanos.min(u64::MAX as u128) as u64))
+    }
+}
+
+/// `rate = pacing_gain * cwnd / smoothed_rtt`, in bytes/sec. The gain is more aggressive during
+/// slow start, where `cwnd` itself is still a poor estimate of the path's real capacity and an
+/// over-eager pacer would otherwise throttle ramp-up.
+fn pacing_rate(cwnd: u64, smoothed_rtt: Duration, in_slow_start: bool) -> u64 {
+    let gain = if in_slow_start {
+        SLOW_START_PACING_GAIN
+    } else {
+        CONGESTION_AVOIDANCE_PACING_GAIN
+    };
+    let rtt_nanos = smoothed_rtt.as_nanos().max(1);
+    ((u128::from(cwnd) * gain.0 as u128 * Duration::from_secs(1).as_nanos())
+        / (gain.1 as u128 * rtt_nanos))
+        .min(u64::MAX as u128) as u64
+}
+
+/// Expressed as a (numerator, denominator) pair to avoid pulling in a floating point dependency
+/// for what is, in the end, a fixed ratio.
+const SLOW_START_PACING_GAIN: (u32, u32) = (2, 1);
+const CONGESTION_AVOIDANCE_PACING_GAIN: (u32, u32) = (5, 4);
+
+/// Burst budget, expressed as a number of MTU-sized packets, that the bucket is allowed to
+/// accumulate while idle.
+const BURST_BUDGET_PACKETS: u64 = 10;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MTU: u64 = 1200;
+    const CWND: u64 = 12_000;
+    const RTT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn starts_with_a_full_burst_budget() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(now, MTU as u16);
+        assert!(pacer.poll_transmit(now, MTU, CWND, RTT, false).is_none());
+    }
+
+    #[test]
+    fn blocks_once_the_burst_budget_is_exhausted() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(now, MTU as u16);
+        for _ in 0..BURST_BUDGET_PACKETS {
+            assert!(pacer.poll_transmit(now, MTU, CWND, RTT, false).is_none());
+        }
+        assert!(pacer.poll_transmit(now, MTU, CWND, RTT, false).is_some());
+    }
+
+    #[test]
+    fn refills_over_time_at_the_configured_rate() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(now, MTU as u16);
+        for _ in 0..BURST_BUDGET_PACKETS {
+            pacer.poll_transmit(now, MTU, CWND, RTT, false);
+        }
+        assert!(pacer.poll_transmit(now, MTU, CWND, RTT, false).is_some());
+
+        let later = now + Duration::from_secs(1);
+        assert!(pacer.poll_transmit(later, MTU, CWND, RTT, false).is_none());
+    }
+}
@@ -1,5 +1,10 @@
-use std::{cmp, net::SocketAddr, time::Duration};
+use std::{
+    cmp,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
+use super::pacing::Pacer;
 use crate::{congestion, TIMER_GRANULARITY};
 
 /// Description of a particular network path
@@ -8,34 +13,191 @@ pub struct PathData {
     pub rtt: RttEstimator,
     /// Whether we're enabling ECN on outgoing packets
     pub sending_ecn: bool,
+    /// The total ECN counts last reported by the peer, used to validate that marked packets are
+    /// actually arriving with their markings intact (RFC 9000 §13.4.2).
+    ecn_feedback: EcnCounts,
     /// Congestion controller state
     pub congestion: Box<dyn congestion::Controller>,
     /// MTU discovery
     pub mtud: MtuDiscovery,
+    /// Paces transmissions over an RTT rather than sending a full congestion window as a burst;
+    /// `None` if pacing has been disabled (the `no_pacing` config knob).
+    pacer: Option<Pacer>,
+    /// PATH_CHALLENGE/PATH_RESPONSE validation state for this path, per RFC 9000 §8.2.
+    pub validation: PathValidation,
+    /// Bytes sent to the peer on this path.
+    total_sent: u64,
+    /// Bytes received from the peer on this path. Together with `total_sent`, enforces the
+    /// RFC 9000 §8.2.1 3x anti-amplification limit while `validation` hasn't completed.
+    total_recvd: u64,
 }
 
 impl PathData {
-    pub fn new(remote: SocketAddr, congestion: Box<dyn congestion::Controller>) -> Self {
+    /// Creates state for a brand new path, e.g. the initial path of a connection or one that a
+    /// migration has been confirmed to land on.
+    pub fn new(
+        remote: SocketAddr,
+        congestion: Box<dyn congestion::Controller>,
+        max_mtu: u16,
+        enable_pacing: bool,
+        initial_rtt: Duration,
+        challenge: u64,
+        now: Instant,
+    ) -> Self {
+        let mtud = MtuDiscovery::new(remote, max_mtu);
         PathData {
             remote,
-            rtt: RttEstimator::new(),
+            rtt: RttEstimator::with_initial(initial_rtt),
             sending_ecn: true,
+            ecn_feedback: EcnCounts::ZERO,
+            pacer: enable_pacing.then(|| Pacer::new(now, mtud.current)),
             congestion,
-            mtud: MtuDiscovery::new(remote),
+            mtud,
+            validation: PathValidation::new(challenge),
+            total_sent: 0,
+            total_recvd: 0,
         }
     }
 
-    pub fn from_previous(remote: SocketAddr, prev: &PathData) -> Self {
+    /// Creates state for a migration to a new path. The RTT estimate is carried over from
+    /// `prev` as a starting guess (with its windowed minimum reseeded, since the old path's
+    /// minimum says nothing about the new one); `congestion` and MTU discovery are always fresh,
+    /// since the new path's capacity is unknown regardless of what `prev` had converged on.
+    pub fn from_previous(
+        remote: SocketAddr,
+        prev: &PathData,
+        congestion: Box<dyn congestion::Controller>,
+        max_mtu: u16,
+        challenge: u64,
+        now: Instant,
+    ) -> Self {
+        let mut rtt = prev.rtt;
+        rtt.on_path_change();
+        let mtud = MtuDiscovery::new(remote, max_mtu);
         PathData {
             remote,
-            rtt: prev.rtt,
-            congestion: prev.congestion.clone_box(),
+            rtt,
+            congestion,
             sending_ecn: true,
-            mtud: MtuDiscovery::new(remote),
+            ecn_feedback: EcnCounts::ZERO,
+            pacer: prev.pacer.is_some().then(|| Pacer::new(now, mtud.current)),
+            mtud,
+            validation: PathValidation::new(challenge),
+            total_sent: 0,
+            total_recvd: 0,
+        }
+    }
+
+    /// Records `size` bytes sent on this path, for anti-amplification accounting.
+    pub fn record_sent(&mut self, size: u64) {
+        self.total_sent += size;
+    }
+
+    /// Records `size` bytes received from the peer on this path, for anti-amplification
+    /// accounting.
+    pub fn record_recvd(&mut self, size: u64) {
+        self.total_recvd += size;
+    }
+
+    /// The number of bytes still permitted to be sent on this path before the 3x
+    /// anti-amplification limit is reached, or `None` if the path is already validated and the
+    /// limit no longer applies.
+    pub fn remaining_amplification_budget(&self) -> Option<u64> {
+        amplification_budget(
+            self.total_sent,
+            self.total_recvd,
+            self.validation.is_validated(),
+        )
+    }
+
+    /// Checks whether a `size`-byte datagram may be sent now under the pacing budget, per
+    /// §7.7 of RFC 9002. Returns `None` if pacing is disabled or the datagram may be sent
+    /// immediately, or `Some(duration)` for how long the caller should wait.
+    pub fn poll_pacing(
+        &mut self,
+        now: Instant,
+        size: u64,
+        in_slow_start: bool,
+    ) -> Option<Duration> {
+        let smoothed_rtt = self.rtt.get();
+        let cwnd = self.congestion.window();
+        self.pacer
+            .as_mut()?
+            .poll_transmit(now, size, cwnd, smoothed_rtt, in_slow_start)
+    }
+
+    /// Validates ECN counts newly reported in an ACK frame against what we've seen before,
+    /// per RFC 9000 §13.4.2. `newly_acked_ecn_marked` is the number of packets covered by this
+    /// ACK that we sent with an ECN marking and had not previously had acknowledged.
+    ///
+    /// Disables `sending_ecn` permanently for this path if validation fails. A CE mark increase
+    /// is treated as a congestion signal and forwarded to the congestion controller.
+    pub fn on_ecn_feedback(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        reported: EcnCounts,
+        newly_acked_ecn_marked: u64,
+    ) {
+        if !self.sending_ecn {
+            return;
+        }
+
+        let ce_increase =
+            match validate_ecn_feedback(self.ecn_feedback, reported, newly_acked_ecn_marked) {
+                Some(ce_increase) => ce_increase,
+                None => {
+                    self.sending_ecn = false;
+                    return;
+                }
+            };
+
+        self.ecn_feedback = reported;
+        if ce_increase > 0 {
+            self.congestion.on_congestion_event(now, sent, false, 0);
         }
     }
 }
 
+/// Per-path-per-peer-report totals of packets observed with each ECN codepoint, as carried in
+/// the ECN counts of an ACK frame (RFC 9000 §19.3.2).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EcnCounts {
+    pub ect0: u64,
+    pub ect1: u64,
+    pub ce: u64,
+}
+
+impl EcnCounts {
+    const ZERO: Self = Self {
+        ect0: 0,
+        ect1: 0,
+        ce: 0,
+    };
+
+    fn total(&self) -> u64 {
+        self.ect0 + self.ect1 + self.ce
+    }
+}
+
+/// Checks newly reported ECN counts against what was previously reported, per RFC 9000 §13.4.2.
+/// Returns the increase in CE-marked packets if the report is valid, or `None` if it isn't
+/// (either the totals regressed, fewer new marks were reported than packets we know arrived
+/// marked, or the CE count itself went backwards).
+fn validate_ecn_feedback(
+    prev: EcnCounts,
+    reported: EcnCounts,
+    newly_acked_ecn_marked: u64,
+) -> Option<u64> {
+    let ce_increase = reported.ce.checked_sub(prev.ce)?;
+    let prev_total = prev.total();
+    let new_total = reported.total();
+    if new_total < prev_total || new_total - prev_total < newly_acked_ecn_marked {
+        return None;
+    }
+    Some(ce_increase)
+}
+
 #[derive(Copy, Clone)]
 pub struct RttEstimator {
     /// The most recent RTT measurement made when receiving an ack for a previously unacked packet
@@ -44,24 +206,46 @@ pub struct RttEstimator {
     smoothed: Option<Duration>,
     /// The RTT variance, computed as described in RFC6298
     var: Duration,
-    /// The minimum RTT seen in the connection, ignoring ack delay.
+    /// The minimum RTT seen in the current window, ignoring ack delay.
     min: Duration,
+    /// Number of samples folded into `min` since it was last reseeded, so the window can be
+    /// reseeded from the latest sample periodically instead of tracking an all-time low.
+    samples_since_min_reset: u32,
 }
 
 impl RttEstimator {
-    fn new() -> Self {
+    /// Returns an estimator seeded with `initial_rtt`, for use on paths whose latency is known
+    /// in advance (e.g. a configured `initial_rtt` transport parameter), so the first PTO
+    /// doesn't fire spuriously early. `min` starts unset rather than at `initial_rtt`, since
+    /// that's a guess, not a measurement.
+    pub fn with_initial(initial_rtt: Duration) -> Self {
         Self {
-            latest: Duration::new(0, 0),
+            latest: initial_rtt,
             smoothed: None,
-            var: Duration::new(0, 0),
-            min: Duration::new(u64::max_value(), 0),
+            var: initial_rtt / 2,
+            min: Duration::new(u64::MAX, 0),
+            samples_since_min_reset: 0,
         }
     }
 
+    /// Reseeds the windowed minimum from the most recent sample, for use when a path change
+    /// makes the current `min` stale.
+    pub fn on_path_change(&mut self) {
+        self.min = self.latest;
+        self.samples_since_min_reset = 0;
+    }
+
     pub fn update(&mut self, ack_delay: Duration, rtt: Duration) {
         self.latest = rtt;
-        // min_rtt ignores ack delay.
-        self.min = cmp::min(self.min, self.latest);
+        // min_rtt ignores ack delay. Reseed the windowed minimum from the latest sample every
+        // `MIN_RTT_WINDOW_SAMPLES` updates instead of tracking an all-time low.
+        self.samples_since_min_reset += 1;
+        if self.samples_since_min_reset >= MIN_RTT_WINDOW_SAMPLES {
+            self.min = self.latest;
+            self.samples_since_min_reset = 0;
+        } else {
+            self.min = cmp::min(self.min, self.latest);
+        }
         // Adjust for ack delay if it's plausible.
         if self.latest - self.min > ack_delay {
             self.latest -= ack_delay;
@@ -92,6 +276,10 @@ impl RttEstimator {
     }
 }
 
+/// How many RTT samples accumulate into the windowed minimum before it's reseeded from the
+/// latest sample.
+const MIN_RTT_WINDOW_SAMPLES: u32 = 64;
+
 // Implements Datagram Packetization Layer Path Maximum Transmission Unit Discovery
 //
 // https://www.ietf.org/id/draft-ietf-tsvwg-datagram-plpmtud-21.html
@@ -105,60 +293,95 @@ pub struct MtuDiscovery {
     // Failed probes at the current probe size
     probe_count: usize,
     phase: Phase,
+    // Consecutive losses of non-probe packets at or near `current`, used for black hole
+    // detection as described in section 3 of the PLPMTUD draft.
+    black_hole_losses: usize,
+    // When the path last reached `Phase::Complete`, used to schedule `PMTU_RAISE_TIMER`
+    // re-validation.
+    last_complete: Option<Instant>,
+    // Binary search bounds on the probe datagram size (including `header_size`). `low` is the
+    // largest size known to get through; `high` is the largest size still worth probing: one
+    // below the smallest size known not to get through (or the ceiling, if nothing has failed
+    // yet).
+    low: u16,
+    high: u16,
+    // Upper bound on `high`, configured by the user (mirrors `path::mtu` in other QUIC stacks).
+    max_mtu: u16,
 }
 
 impl MtuDiscovery {
-    fn new(remote: SocketAddr) -> Self {
+    fn new(remote: SocketAddr, max_mtu: u16) -> Self {
+        let header_size = match remote {
+            SocketAddr::V4(_) => 20,
+            SocketAddr::V6(_) => 48,
+        };
+        let max_mtu = cmp::min(max_mtu, MAX_PLPMTU);
         Self {
-            header_size: match remote {
-                SocketAddr::V4(_) => 20,
-                SocketAddr::V6(_) => 48,
-            },
+            header_size,
             current: BASE_PLPMTU,
             probe_number: None,
             probe_size: None,
             probe_count: 0,
             phase: Phase::Searching,
+            black_hole_losses: 0,
+            last_complete: None,
+            low: BASE_PLPMTU + header_size,
+            high: max_mtu,
+            max_mtu,
         }
     }
 
-    pub fn poll_transmit(&mut self, next_packet_number: u64) -> Option<u16> {
+    pub fn poll_transmit(&mut self, now: Instant, next_packet_number: u64) -> Option<u16> {
         if self.probe_number.is_some() {
             return None;
-        } else if let Phase::Complete = self.phase {
-            return None;
         }
 
-        if self.probe_size.is_none() {
-            match LEVELS
-                .iter()
-                .find(|&&x| x > (self.current + self.header_size))
-            {
-                Some(v) => {
-                    self.probe_size = Some(*v);
-                }
-                None => {
-                    self.phase = Phase::Complete;
-                    return None;
+        if let Phase::Complete = self.phase {
+            match self.last_complete {
+                // Periodically re-probe upward in case the path's effective MTU has increased.
+                Some(completed) if now.saturating_duration_since(completed) >= PMTU_RAISE_TIMER => {
+                    self.phase = Phase::Searching;
+                    self.last_complete = None;
+                    self.high = self.max_mtu;
                 }
+                _ => return None,
             }
         }
 
+        if self.probe_size.is_none() {
+            if self.high <= self.low || self.high - self.low < BINARY_SEARCH_STEP {
+                self.phase = Phase::Complete;
+                self.last_complete = Some(now);
+                return None;
+            }
+
+            let mid = self.low + (self.high - self.low) / 2;
+            // Round down to the step granularity, and make sure we always make forward progress.
+            let mid = cmp::max(
+                mid - (mid % BINARY_SEARCH_STEP),
+                self.low + BINARY_SEARCH_STEP,
+            );
+            self.probe_size = Some(mid);
+        }
+
         self.probe_number = Some(next_packet_number);
         self.probe_size
     }
 
-    pub fn acked(&mut self, number: u64) {
+    pub fn acked(&mut self, now: Instant, number: u64) {
         match self.probe_number {
             Some(probed) if probed == number => {}
             _ => return,
         };
 
         self.probe_number = None;
+        self.probe_count = 0;
         let new = self.probe_size.take().unwrap();
+        self.low = new;
         self.current = new - self.header_size;
-        if self.current == MAX_PLPMTU {
+        if self.high - self.low < BINARY_SEARCH_STEP {
             self.phase = Phase::Complete;
+            self.last_complete = Some(now);
         }
     }
 
@@ -168,22 +391,335 @@ impl MtuDiscovery {
             _ => return,
         };
 
+        let probed = self.probe_size.take().unwrap();
         self.probe_number = None;
         self.probe_count += 1;
         if self.probe_count == MAX_PROBES {
-            self.probe_size = None;
-            self.phase = Phase::Complete;
+            self.probe_count = 0;
+            self.high = probed - 1;
         }
     }
+
+    /// Called by the loss detection path whenever an ordinary (non-probe) packet is newly
+    /// acknowledged, so the black hole detector can reset its streak.
+    pub fn on_non_probe_acked(&mut self, size: u16) {
+        if size >= self.current {
+            self.black_hole_losses = 0;
+        }
+    }
+
+    /// Called by the loss detection path whenever an ordinary (non-probe) packet is declared
+    /// lost. Implements the black-hole-detection half of DPLPMTUD: if enough consecutive losses
+    /// of packets at or near `current` are observed, the path is assumed to be newly
+    /// black-holing full-size datagrams, and MTU discovery is restarted from `BASE_PLPMTU`.
+    ///
+    /// `size` is the header-exclusive packet size, in the same units as `current`.
+    pub fn on_non_probe_lost(&mut self, size: u16) {
+        if size < self.current.saturating_sub(BLACK_HOLE_SIZE_TOLERANCE) {
+            // Too small to be evidence of a black hole at the current MTU.
+            return;
+        }
+
+        self.black_hole_losses += 1;
+        if self.black_hole_losses < BLACK_HOLE_THRESHOLD {
+            return;
+        }
+
+        self.black_hole_losses = 0;
+        self.current = BASE_PLPMTU;
+        self.low = BASE_PLPMTU + self.header_size;
+        self.high = self.max_mtu;
+        self.probe_number = None;
+        self.probe_size = None;
+        self.probe_count = 0;
+        self.last_complete = None;
+        self.phase = Phase::Searching;
+    }
 }
 
+#[derive(Copy, Clone)]
 enum Phase {
     Searching,
     Complete,
 }
 
-const LEVELS: [u16; 4] = [1_350, 1_400, 1_450, 1_500];
-
 const MAX_PROBES: usize = 3;
 const MAX_PLPMTU: u16 = u16::MAX;
 const BASE_PLPMTU: u16 = 1280;
+
+/// Granularity of the binary search over probe sizes: search stops once the gap between `low`
+/// and `high` is smaller than this, rather than chasing single-byte precision.
+const BINARY_SEARCH_STEP: u16 = 20;
+
+/// Default ceiling for [`MtuDiscovery::high`] when the user hasn't configured a `max_mtu`.
+/// Matches the common Ethernet MTU of 1500 bytes; users on jumbo-frame-capable paths can raise
+/// it explicitly.
+pub(crate) const DEFAULT_MAX_MTU: u16 = 1500;
+
+/// Number of consecutive non-probe packet losses at or near the current PLPMTU that are treated
+/// as a black hole, per the guidance in section 3.3 of the PLPMTUD draft.
+const BLACK_HOLE_THRESHOLD: usize = 3;
+
+/// How close to `current` a lost packet's size must be to count as evidence of a black hole.
+const BLACK_HOLE_SIZE_TOLERANCE: u16 = 32;
+
+/// How long a path stays at `Phase::Complete` before DPLPMTUD re-probes upward, in case the
+/// path's effective MTU has increased.
+const PMTU_RAISE_TIMER: Duration = Duration::from_secs(600);
+
+/// Tracks PATH_CHALLENGE/PATH_RESPONSE validation for a path, per RFC 9000 §8.2. A path starts
+/// out unvalidated; until [`PathValidation::is_validated`] returns `true`, callers must enforce
+/// the 3x anti-amplification limit (see [`PathData::remaining_amplification_budget`]) and must
+/// not treat the path as trusted for migration purposes.
+pub struct PathValidation {
+    /// The 8-byte token sent in our PATH_CHALLENGE and expected back in the peer's
+    /// PATH_RESPONSE.
+    token: u64,
+    /// Remaining transmissions budgeted for this challenge, including the initial one;
+    /// decremented on each send.
+    remaining_probes: u32,
+    validated: bool,
+}
+
+impl PathValidation {
+    fn new(token: u64) -> Self {
+        Self {
+            token,
+            remaining_probes: MAX_PATH_PROBES,
+            validated: false,
+        }
+    }
+
+    pub fn is_validated(&self) -> bool {
+        self.validated
+    }
+
+    /// Called when a PATH_CHALLENGE frame should be (re)sent: on initial path creation, and on
+    /// each PTO until the path validates or the probe budget is exhausted. Returns the token to
+    /// send, or `None` if there's nothing left to do.
+    pub fn poll_transmit(&mut self) -> Option<u64> {
+        if self.validated || self.remaining_probes == 0 {
+            return None;
+        }
+        self.remaining_probes -= 1;
+        Some(self.token)
+    }
+
+    /// Called when a PATH_RESPONSE frame is received on any path. Returns `true` exactly once,
+    /// the first time the response matches this path's outstanding challenge -- callers should
+    /// treat that transition as the signal to surface a path-validated connection event.
+    pub fn on_response(&mut self, token: u64) -> bool {
+        if self.validated || token != self.token {
+            return false;
+        }
+        self.validated = true;
+        true
+    }
+}
+
+/// Transmissions of a PATH_CHALLENGE (the initial send included) before giving up on validating a path, mirrored
+/// on the PTO schedule like initial MTU probes.
+const MAX_PATH_PROBES: u32 = 3;
+
+/// We may send at most this many times what we've received from the peer on an unvalidated
+/// path, per the RFC 9000 §8.2.1 anti-amplification limit.
+const ANTI_AMPLIFICATION_FACTOR: u64 = 3;
+
+/// The RFC 9000 §8.2.1 3x anti-amplification limit, as a pure function of the raw byte counters
+/// and validation state, so it's exercisable without constructing a full [`PathData`].
+fn amplification_budget(total_sent: u64, total_recvd: u64, validated: bool) -> Option<u64> {
+    if validated {
+        return None;
+    }
+    Some((total_recvd * ANTI_AMPLIFICATION_FACTOR).saturating_sub(total_sent))
+}
+
+#[cfg(test)]
+mod mtud_tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn addr() -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0)
+    }
+
+    #[test]
+    fn binary_search_converges_when_every_probe_succeeds() {
+        let now = Instant::now();
+        let mut mtud = MtuDiscovery::new(addr(), 1500);
+        let mut next_pn = 0u64;
+
+        for _ in 0..32 {
+            match mtud.poll_transmit(now, next_pn) {
+                Some(_) => {
+                    mtud.acked(now, next_pn);
+                    next_pn += 1;
+                }
+                None => break,
+            }
+        }
+
+        assert!(mtud.poll_transmit(now, next_pn).is_none());
+        assert!(mtud.current >= BASE_PLPMTU);
+        assert!(mtud.current + mtud.header_size <= 1500);
+    }
+
+    #[test]
+    fn binary_search_shrinks_high_on_repeated_loss() {
+        let now = Instant::now();
+        let mut mtud = MtuDiscovery::new(addr(), 1500);
+        let mut next_pn = 0u64;
+
+        for _ in 0..64 {
+            match mtud.poll_transmit(now, next_pn) {
+                Some(probe_size) => {
+                    // Anything above 1400 is simulated as black-holed.
+                    if probe_size > 1400 {
+                        mtud.lost(next_pn);
+                    } else {
+                        mtud.acked(now, next_pn);
+                    }
+                    next_pn += 1;
+                }
+                None => break,
+            }
+        }
+
+        assert!(mtud.poll_transmit(now, next_pn).is_none());
+        assert!(mtud.current + mtud.header_size <= 1400);
+    }
+}
+
+#[cfg(test)]
+mod ecn_tests {
+    use super::*;
+
+    #[test]
+    fn accepts_matching_totals_with_no_ce_increase() {
+        let prev = EcnCounts {
+            ect0: 10,
+            ect1: 0,
+            ce: 0,
+        };
+        let reported = EcnCounts {
+            ect0: 12,
+            ect1: 0,
+            ce: 0,
+        };
+        assert_eq!(validate_ecn_feedback(prev, reported, 2), Some(0));
+    }
+
+    #[test]
+    fn reports_the_ce_increase() {
+        let prev = EcnCounts {
+            ect0: 10,
+            ect1: 0,
+            ce: 1,
+        };
+        let reported = EcnCounts {
+            ect0: 10,
+            ect1: 0,
+            ce: 3,
+        };
+        assert_eq!(validate_ecn_feedback(prev, reported, 2), Some(2));
+    }
+
+    #[test]
+    fn rejects_a_total_that_went_backwards() {
+        let prev = EcnCounts {
+            ect0: 10,
+            ect1: 0,
+            ce: 1,
+        };
+        let reported = EcnCounts {
+            ect0: 8,
+            ect1: 0,
+            ce: 1,
+        };
+        assert_eq!(validate_ecn_feedback(prev, reported, 0), None);
+    }
+
+    #[test]
+    fn rejects_fewer_new_marks_than_newly_acked_packets_carried() {
+        let prev = EcnCounts {
+            ect0: 10,
+            ect1: 0,
+            ce: 0,
+        };
+        let reported = EcnCounts {
+            ect0: 11,
+            ect1: 0,
+            ce: 0,
+        };
+        // Two newly-acked packets were marked, but the peer only reports one new count.
+        assert_eq!(validate_ecn_feedback(prev, reported, 2), None);
+    }
+
+    #[test]
+    fn rejects_a_ce_count_that_went_backwards() {
+        let prev = EcnCounts {
+            ect0: 0,
+            ect1: 0,
+            ce: 5,
+        };
+        let reported = EcnCounts {
+            ect0: 0,
+            ect1: 0,
+            ce: 3,
+        };
+        assert_eq!(validate_ecn_feedback(prev, reported, 0), None);
+    }
+}
+
+#[cfg(test)]
+mod path_validation_tests {
+    use super::*;
+
+    #[test]
+    fn unvalidated_budget_is_three_times_received_minus_sent() {
+        assert_eq!(amplification_budget(0, 100, false), Some(300));
+        assert_eq!(amplification_budget(250, 100, false), Some(50));
+    }
+
+    #[test]
+    fn budget_saturates_at_zero_rather_than_underflowing() {
+        assert_eq!(amplification_budget(1_000, 100, false), Some(0));
+    }
+
+    #[test]
+    fn validated_paths_have_no_budget_limit() {
+        assert_eq!(amplification_budget(1_000_000, 0, true), None);
+    }
+
+    #[test]
+    fn challenge_is_retransmitted_up_to_the_probe_budget_then_gives_up() {
+        let mut validation = PathValidation::new(0x1234_5678);
+        for _ in 0..MAX_PATH_PROBES {
+            assert_eq!(validation.poll_transmit(), Some(0x1234_5678));
+        }
+        assert_eq!(validation.poll_transmit(), None);
+    }
+
+    #[test]
+    fn matching_response_validates_exactly_once() {
+        let mut validation = PathValidation::new(42);
+        assert!(validation.on_response(42));
+        assert!(validation.is_validated());
+        // A second, redundant response shouldn't re-fire the validated transition.
+        assert!(!validation.on_response(42));
+    }
+
+    #[test]
+    fn mismatched_response_does_not_validate() {
+        let mut validation = PathValidation::new(42);
+        assert!(!validation.on_response(7));
+        assert!(!validation.is_validated());
+    }
+
+    #[test]
+    fn validated_path_stops_sending_challenges() {
+        let mut validation = PathValidation::new(42);
+        validation.on_response(42);
+        assert_eq!(validation.poll_transmit(), None);
+    }
+}